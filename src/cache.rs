@@ -0,0 +1,97 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::Mutex as AsyncMutex;
+
+/// Content-addressed cache of terminal run results, keyed by a hash over
+/// everything that determines a check's outcome (language, solution,
+/// checker, asserts, Makefile/toolchain). Only deterministic, complete runs
+/// are worth storing here; timeouts and internal errors are never written.
+pub struct ResultCache {
+    dir: PathBuf,
+    // One lock per key currently being computed, so that concurrent misses
+    // for the same submission run the check once instead of racing.
+    locks: Mutex<HashMap<String, Arc<AsyncMutex<()>>>>,
+}
+
+impl ResultCache {
+    pub fn new(dir: PathBuf) -> std::io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            locks: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+
+    /// Returns the cached value for `key`, if one was written before.
+    pub fn get<T: serde::de::DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let bytes = fs::read(self.entry_path(key)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    /// Stores `value` under `key`, overwriting any previous entry.
+    pub fn put<T: serde::Serialize>(&self, key: &str, value: &T) -> std::io::Result<()> {
+        let bytes =
+            serde_json::to_vec(value).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        fs::write(self.entry_path(key), bytes)
+    }
+
+    /// Returns a lock for `key` that automatically forgets its own registry
+    /// entry when dropped, so callers don't need to remember to call
+    /// `forget` on every exit path (success, cache hit, or any error).
+    pub fn lock_key<'a>(&'a self, key: &str) -> CacheKeyLock<'a> {
+        let lock = self
+            .locks
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_insert_with(|| Arc::new(AsyncMutex::new(())))
+            .clone();
+        CacheKeyLock {
+            cache: self,
+            key: key.to_string(),
+            lock,
+        }
+    }
+
+    /// Drops the bookkeeping for `key`'s lock once nobody else is waiting on
+    /// it, so the registry doesn't grow forever. Only two references are
+    /// expected to remain at this point: the one stored in `locks` and
+    /// `lock` itself (the caller's own, about to be dropped) — anything
+    /// more means another task is still waiting on this key.
+    fn forget(&self, key: &str, lock: &Arc<AsyncMutex<()>>) {
+        let mut locks = self.locks.lock().unwrap();
+        if Arc::strong_count(lock) <= 2 {
+            locks.remove(key);
+        }
+    }
+}
+
+/// Holds the per-key lock returned by `ResultCache::lock_key`. Call
+/// `acquire` to actually lock it; dropping this (by any means, including
+/// an early `?` return) forgets the key's registry entry.
+pub struct CacheKeyLock<'a> {
+    cache: &'a ResultCache,
+    key: String,
+    lock: Arc<AsyncMutex<()>>,
+}
+
+impl CacheKeyLock<'_> {
+    pub async fn acquire(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.lock.lock().await
+    }
+}
+
+impl Drop for CacheKeyLock<'_> {
+    fn drop(&mut self) {
+        self.cache.forget(&self.key, &self.lock);
+    }
+}
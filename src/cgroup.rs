@@ -0,0 +1,102 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use nix::unistd::Pid;
+use uuid::Uuid;
+
+/// Resource caps applied to a single `/run`, backing both the cgroup v2
+/// controllers and the `setrlimit` calls made in the child's `pre_exec`.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub memory_bytes: u64,
+    pub pids_max: u64,
+    pub cpu_percent: u32,
+    pub output_bytes: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            memory_bytes: 512 * 1024 * 1024,
+            pids_max: 256,
+            cpu_percent: 100,
+            output_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// A dedicated cgroup v2 directory created for a single `/run`. Caps memory,
+/// CPU share and process count for the check, and lets us ask the kernel
+/// afterwards whether it had to OOM-kill something inside it.
+pub struct CGroup {
+    path: PathBuf,
+}
+
+impl CGroup {
+    /// Creates `/sys/fs/cgroup/<uuid>` and writes the controller limits into
+    /// it. The cgroup starts out empty; the check process moves itself in
+    /// via `add_pid_to_cgroup` from its own `pre_exec`, before it can fork
+    /// again, so membership is never racing an async caller (see `path`).
+    pub fn new(limits: &Limits) -> std::io::Result<Self> {
+        let path = PathBuf::from("/sys/fs/cgroup").join(Uuid::new_v4().to_string());
+        fs::create_dir(&path)?;
+
+        fs::write(path.join("memory.max"), limits.memory_bytes.to_string())?;
+
+        let period = 100_000u64;
+        let quota = period * limits.cpu_percent as u64 / 100;
+        fs::write(path.join("cpu.max"), format!("{} {}", quota, period))?;
+
+        fs::write(path.join("pids.max"), limits.pids_max.to_string())?;
+
+        Ok(Self { path })
+    }
+
+    /// This cgroup's own directory. Exposed so a forked child's `pre_exec`
+    /// can move *itself* in with `add_pid_to_cgroup` before forking again —
+    /// `pre_exec` can't safely capture the owning `CGroup` itself, since
+    /// its `Drop` must only run once, in the parent.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns `true` if the kernel OOM-killed a process in this cgroup.
+    pub fn oom_killed(&self) -> bool {
+        fs::read_to_string(self.path.join("memory.events"))
+            .ok()
+            .and_then(|events| {
+                events
+                    .lines()
+                    .find_map(|line| line.strip_prefix("oom_kill "))
+                    .and_then(|n| n.trim().parse::<u64>().ok())
+            })
+            .is_some_and(|count| count > 0)
+    }
+
+    /// Peak memory usage observed for this cgroup, in bytes, if the kernel
+    /// exposes `memory.peak` (added in Linux 5.19).
+    pub fn peak_memory(&self) -> Option<u64> {
+        fs::read_to_string(self.path.join("memory.peak"))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+}
+
+/// Writes `pid` into `cgroup_path`'s `cgroup.procs`. A free function (not a
+/// `CGroup` method) so it can be called with just the path, copied out of
+/// a `CGroup` before it's moved into a `pre_exec` closure.
+pub fn add_pid_to_cgroup(cgroup_path: &Path, pid: Pid) -> std::io::Result<()> {
+    fs::write(cgroup_path.join("cgroup.procs"), pid.to_string())
+}
+
+impl Drop for CGroup {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir(&self.path) {
+            log::error!("remove cgroup dir: {}", e);
+        }
+    }
+}
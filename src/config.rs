@@ -0,0 +1,108 @@
+use std::{collections::HashMap, fs};
+
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+
+fn default_true() -> bool {
+    true
+}
+
+/// Caps applied to a single language on top of whatever the caller asks
+/// for in the payload. A `None` cap leaves that value unrestricted by the
+/// policy: the caller's value, or the built-in default if the payload
+/// omits it, is used as is.
+#[derive(Debug, Deserialize)]
+pub struct LanguagePolicy {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub max_timeout_secs: Option<u64>,
+    pub max_memory_bytes: Option<u64>,
+}
+
+/// Runtime config loaded once at startup: the set of bearer tokens allowed
+/// to call `/run` and `/run/stream`, and a per-language policy keyed by
+/// the same lowercase slug used in `Payload::lang_slug`.
+#[derive(Debug, Deserialize, Default)]
+pub struct Config {
+    #[serde(default)]
+    tokens: Vec<String>,
+    #[serde(default)]
+    languages: HashMap<String, LanguagePolicy>,
+}
+
+impl Config {
+    /// Loads the config from `RUNNER_CONFIG_PATH` (default
+    /// `/etc/runner/config.json`). A missing file is treated as an empty
+    /// config, i.e. no languages disabled and auth disabled (see
+    /// `tokens_configured`) — convenient for local development.
+    pub fn load() -> std::io::Result<Self> {
+        let path = std::env::var("RUNNER_CONFIG_PATH")
+            .unwrap_or_else(|_| "/etc/runner/config.json".to_string());
+
+        match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether bearer auth should be enforced at all. With no tokens
+    /// configured, the service stays open, same as before this config
+    /// subsystem existed.
+    pub fn tokens_configured(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    /// Compares `token` against every configured token in constant time,
+    /// so a caller can't use response timing to guess a valid token byte
+    /// by byte.
+    pub fn is_authorized(&self, token: &str) -> bool {
+        self.tokens
+            .iter()
+            .any(|t| t.as_bytes().ct_eq(token.as_bytes()).into())
+    }
+
+    /// Returns the policy configured for `lang`, if any. An unlisted
+    /// language has no extra restrictions.
+    pub fn policy_for(&self, lang: &str) -> Option<&LanguagePolicy> {
+        self.languages.get(lang)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with_tokens(tokens: &[&str]) -> Config {
+        Config {
+            tokens: tokens.iter().map(|t| t.to_string()).collect(),
+            languages: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn is_authorized_accepts_a_configured_token() {
+        let config = config_with_tokens(&["good-token"]);
+        assert!(config.is_authorized("good-token"));
+    }
+
+    #[test]
+    fn is_authorized_rejects_an_unknown_token() {
+        let config = config_with_tokens(&["good-token"]);
+        assert!(!config.is_authorized("bad-token"));
+    }
+
+    #[test]
+    fn is_authorized_rejects_everything_with_no_tokens_configured() {
+        let config = config_with_tokens(&[]);
+        assert!(!config.is_authorized(""));
+        assert!(!config.is_authorized("anything"));
+    }
+
+    #[test]
+    fn tokens_configured_reflects_whether_any_tokens_are_set() {
+        assert!(!config_with_tokens(&[]).tokens_configured());
+        assert!(config_with_tokens(&["a"]).tokens_configured());
+    }
+}
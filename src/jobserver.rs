@@ -0,0 +1,33 @@
+use std::os::fd::{AsRawFd, OwnedFd};
+
+use nix::unistd::{pipe, write};
+
+/// A GNU make jobserver: a pipe pre-loaded with one-byte tokens so that
+/// `make` invocations across concurrent `/run` requests share a single pool
+/// of parallel job slots instead of each spawning its own unbounded `-jN`
+/// compiler fan-out.
+pub struct Jobserver {
+    read_fd: OwnedFd,
+    write_fd: OwnedFd,
+}
+
+impl Jobserver {
+    /// Creates the backing pipe and fills it with `slots` tokens.
+    pub fn new(slots: usize) -> nix::Result<Self> {
+        let (read_fd, write_fd) = pipe()?;
+        for _ in 0..slots {
+            write(&write_fd, b"+")?;
+        }
+        Ok(Self { read_fd, write_fd })
+    }
+
+    /// The `--jobserver-auth=R,W` argument to pass to a child `make`, giving
+    /// it (and any `$(MAKE)` sub-invocations it spawns) access to the pool.
+    pub fn auth_arg(&self) -> String {
+        format!(
+            "--jobserver-auth={},{}",
+            self.read_fd.as_raw_fd(),
+            self.write_fd.as_raw_fd()
+        )
+    }
+}
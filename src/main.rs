@@ -1,8 +1,24 @@
 use std::{fs, path::PathBuf, process::Stdio, time::Duration};
 
-use actix_web::{App, HttpResponse, HttpServer, Responder, get, post, web};
+use actix_web::{
+    App, HttpResponse, HttpServer, Responder,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    get,
+    middleware::Next,
+    post, web,
+};
+use base64::Engine;
+use cache::ResultCache;
+use cgroup::{CGroup, Limits};
+use config::Config;
+use jobserver::Jobserver;
 use nix::{
-    sys::{signal, wait::WaitStatus},
+    sys::{
+        resource::{Resource, setrlimit},
+        signal,
+        wait::WaitStatus,
+    },
     unistd::{ForkResult, Pid},
 };
 use serde::{Deserialize, Serialize};
@@ -12,17 +28,37 @@ use signal_hook::{
 };
 use tmpdir::TmpDir;
 use tokio::{
-    io::{AsyncRead, AsyncReadExt},
-    process::Command,
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    process::{Child, ChildStdin, Command},
     runtime::Runtime,
+    sync::{Semaphore, mpsc},
     task, time,
 };
+use tokio_stream::wrappers::ReceiverStream;
 
+mod cache;
+mod cgroup;
+mod config;
+mod jobserver;
 mod tmpdir;
 
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Reads an env var as a positive integer, falling back to the number of
+/// available CPUs (and ultimately to 1) if it is unset or invalid.
+fn concurrency_from_env(var: &str) -> usize {
+    std::env::var(var)
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        })
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 enum Lang {
@@ -51,6 +87,13 @@ struct Payload {
     lang_slug: Lang,
     asserts: Option<String>,
     checker_text: Option<String>,
+    memory_limit: Option<u64>,
+    pids_limit: Option<u64>,
+    cpu_limit: Option<u32>,
+    stdin: Option<String>,
+    files_tar: Option<String>,
+    #[serde(default)]
+    return_artifacts: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -58,6 +101,19 @@ struct Response {
     exit_code: Option<i32>,
     stdout: String,
     stderr: String,
+    oom: bool,
+    peak_memory: Option<u64>,
+    artifacts_tar: Option<String>,
+}
+
+fn limits_from_payload(payload: &Payload) -> Limits {
+    let defaults = Limits::default();
+    Limits {
+        memory_bytes: payload.memory_limit.unwrap_or(defaults.memory_bytes),
+        pids_max: payload.pids_limit.unwrap_or(defaults.pids_max),
+        cpu_percent: payload.cpu_limit.unwrap_or(defaults.cpu_percent),
+        ..defaults
+    }
 }
 
 fn ashy_slashy(child: Pid, mut sig: Signals) {
@@ -99,12 +155,64 @@ fn ashy_slashy(child: Pid, mut sig: Signals) {
     std::process::exit(1);
 }
 
+/// Holds the pid of the namespace's PID 1 so the signal handler
+/// `forward_sigterm`, which cannot capture state, has something to kill.
+static NS_INIT_CHILD: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(0);
+
+extern "C" fn forward_sigterm(_: std::os::raw::c_int) {
+    let pid = NS_INIT_CHILD.load(std::sync::atomic::Ordering::SeqCst);
+    if pid != 0 {
+        let _ = signal::kill(Pid::from_raw(pid), signal::SIGTERM);
+    }
+}
+
+/// Runs in place of `make` as the process Rust actually tracks as the
+/// spawned child (`Child::id`/`wait` in `run`/`run_stream` operate on this
+/// pid). Its only job is to be PID 1's parent: wait for `ns_pid1` to exit
+/// and mirror its exit status, relaying SIGTERM to it in the meantime.
+/// SIGKILL needs no relaying since it already reaches `ns_pid1` directly
+/// through the shared process group set up in `prepare_run`.
+///
+/// Never returns: when `ns_pid1` (the new PID namespace's init) exits, the
+/// kernel tears the namespace down and kills every descendant `make` may
+/// have leaked, closing the leak the old `killpg`-only path had.
+fn ns_init(ns_pid1: Pid) -> ! {
+    NS_INIT_CHILD.store(ns_pid1.as_raw(), std::sync::atomic::Ordering::SeqCst);
+    unsafe {
+        let _ = signal::signal(signal::SIGTERM, signal::SigHandler::Handler(forward_sigterm));
+    }
+
+    loop {
+        match nix::sys::wait::waitpid(ns_pid1, None) {
+            Ok(WaitStatus::Exited(_, code)) => std::process::exit(code),
+            Ok(WaitStatus::Signaled(_, sig, _)) => std::process::exit(128 + sig as i32),
+            Ok(_) => continue,
+            Err(nix::errno::Errno::EINTR) => continue,
+            Err(e) => {
+                log::error!("ns_init: waitpid failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
 async fn read_stdio<R: AsyncRead + Unpin>(mut reader: R) -> std::io::Result<String> {
     let mut buf = String::new();
     reader.read_to_string(&mut buf).await?;
     Ok(buf)
 }
 
+/// Writes `data` (if any) to the child's stdin, then drops it to close the
+/// write end so the child sees EOF, same as the old `Stdio::null()` did
+/// when no stdin was supplied.
+async fn write_stdin(mut stdin: ChildStdin, data: Option<String>) {
+    if let Some(data) = data {
+        if let Err(e) = stdin.write_all(data.as_bytes()).await {
+            log::error!("Write child stdin: {}", e);
+        }
+    }
+}
+
 fn get_solution_chekcer_names(payload: &Payload) -> (&str, Option<&str>) {
     match payload.lang_slug {
         Lang::Clojure => ("solution.clj", None),
@@ -126,12 +234,147 @@ fn get_solution_chekcer_names(payload: &Payload) -> (&str, Option<&str>) {
     }
 }
 
-#[post("/run")]
-async fn run(
-    web::Json(payload): web::Json<Payload>,
-) -> Result<web::Json<Response>, actix_web::Error> {
-    log::debug!("{}", serde_json::to_string(&payload).unwrap());
-    let timeout = match payload.timeout {
+fn check_dir_for(lang: &Lang) -> &'static str {
+    if matches!(lang, Lang::Dart) { "lib" } else { "check" }
+}
+
+/// The slug used to key a language's policy in the config file, matching
+/// `Payload::lang_slug`'s `#[serde(rename_all = "lowercase")]` spelling.
+fn lang_key(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::Clojure => "clojure",
+        Lang::Cpp => "cpp",
+        Lang::Csharp => "csharp",
+        Lang::Dart => "dart",
+        Lang::Elixir => "elixir",
+        Lang::Golang => "golang",
+        Lang::Haskell => "haskell",
+        Lang::Java => "java",
+        Lang::Js => "js",
+        Lang::Kotlin => "kotlin",
+        Lang::Php => "php",
+        Lang::Python => "python",
+        Lang::Ruby => "ruby",
+        Lang::Rust => "rust",
+        Lang::Swift => "swift",
+        Lang::Ts => "ts",
+    }
+}
+
+/// Mirrors `cwd`'s check dir onto an overlay root (the merged chroot or the
+/// upper dir), dropping the leading `/` so the join doesn't replace `root`.
+fn overlay_check_path(root: &std::path::Path, cwd: &std::path::Path, check_dir: &str) -> PathBuf {
+    root.join(PathBuf::from_iter(cwd.components().skip(1)))
+        .join(check_dir)
+}
+
+/// Resolves `relative` (a caller-supplied `return_artifacts` entry) against
+/// `check_path`, rejecting anything that could escape it: absolute paths
+/// discard `check_path` entirely under `PathBuf::join`'s semantics, and
+/// `..` components walk back out of it even when joined. Returns `None`
+/// for either case, or if the joined path somehow still isn't inside
+/// `check_path`.
+fn resolve_artifact_path(check_path: &std::path::Path, relative: &str) -> Option<PathBuf> {
+    let relative = std::path::Path::new(relative);
+    if relative.components().any(|c| {
+        matches!(
+            c,
+            std::path::Component::RootDir
+                | std::path::Component::ParentDir
+                | std::path::Component::Prefix(_)
+        )
+    }) {
+        return None;
+    }
+
+    let resolved = check_path.join(relative);
+    resolved.starts_with(check_path).then_some(resolved)
+}
+
+/// Reads back `wanted` paths (relative to the check directory) from the
+/// overlay's upper dir and packs the ones that exist into a base64 tar.
+/// Paths that weren't produced by the run, or that try to escape the
+/// check directory, are skipped rather than failing the whole request.
+fn pack_artifacts(check_path: &std::path::Path, wanted: &[String]) -> Option<String> {
+    if wanted.is_empty() {
+        return None;
+    }
+
+    let mut buf = Vec::new();
+    {
+        let mut builder = tar::Builder::new(&mut buf);
+        for path in wanted {
+            let Some(full) = resolve_artifact_path(check_path, path) else {
+                log::warn!("Skip artifact outside the check dir: {}", path);
+                continue;
+            };
+            if let Err(e) = builder.append_path_with_name(full, path) {
+                log::warn!("Skip missing artifact {}: {}", path, e);
+            }
+        }
+        if let Err(e) = builder.finish() {
+            log::error!("Finish artifacts tar: {}", e);
+            return None;
+        }
+    }
+
+    Some(base64::engine::general_purpose::STANDARD.encode(buf))
+}
+
+/// BLAKE3 hash over everything that determines the outcome of a check:
+/// language, solution, checker, asserts, stdin, extra files, the requested
+/// timeout and resource limits, and the Makefile (which pins the toolchain)
+/// for that language, plus which artifacts the caller wants back. Used as
+/// the result cache key.
+fn cache_key(payload: &Payload) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(format!("{:?}", payload.lang_slug).as_bytes());
+    hasher.update(payload.solution_text.as_bytes());
+    hasher.update(payload.checker_text.as_deref().unwrap_or("").as_bytes());
+    hasher.update(payload.asserts.as_deref().unwrap_or("").as_bytes());
+    hasher.update(payload.stdin.as_deref().unwrap_or("").as_bytes());
+    hasher.update(payload.files_tar.as_deref().unwrap_or("").as_bytes());
+    // Debug-formatted so `None` and `Some(0)` hash differently.
+    hasher.update(format!("{:?}", payload.timeout).as_bytes());
+    hasher.update(format!("{:?}", payload.memory_limit).as_bytes());
+    hasher.update(format!("{:?}", payload.pids_limit).as_bytes());
+    hasher.update(format!("{:?}", payload.cpu_limit).as_bytes());
+    for artifact in &payload.return_artifacts {
+        hasher.update(artifact.as_bytes());
+        hasher.update(b"\0");
+    }
+
+    if let Ok(cwd) = std::env::current_dir() {
+        let makefile = cwd.join(check_dir_for(&payload.lang_slug)).join("Makefile");
+        if let Ok(contents) = fs::read(makefile) {
+            hasher.update(&contents);
+        }
+    }
+
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Everything needed to run the check that is shared between `/run` and
+/// `/run/stream`: the jail and cgroup (both kept alive for the lifetime of
+/// the child) and a `make test` command ready to spawn. Timeout and limits
+/// are resolved separately by `check_policy` before this is built.
+struct PreparedRun {
+    tmp: TmpDir,
+    cgroup: CGroup,
+    cmd: Command,
+}
+
+/// Resolves the timeout and resource limits for `payload` and checks them
+/// against `config`'s per-language policy: a disabled language is rejected
+/// with 403. A value the caller explicitly asked for that's above the
+/// configured cap is rejected with 400; a value left at its built-in
+/// default is clamped down to the cap instead, so configuring a strict
+/// policy doesn't start rejecting ordinary requests that never mentioned
+/// `timeout`/`memory_limit` at all. Called before the result cache is even
+/// consulted, so a cache hit can never bypass policy that would reject the
+/// same request fresh.
+fn check_policy(payload: &Payload, config: &Config) -> Result<(Duration, Limits), actix_web::Error> {
+    let mut timeout = match payload.timeout {
         Some(ref t) => duration_str::parse(t).map_err(|e| {
             log::error!("Parse timeout: {}", e);
             actix_web::error::ErrorBadRequest("wrong timeout format")
@@ -139,6 +382,39 @@ async fn run(
         None => DEFAULT_TIMEOUT,
     };
 
+    let mut limits = limits_from_payload(payload);
+
+    if let Some(policy) = config.policy_for(lang_key(&payload.lang_slug)) {
+        if !policy.enabled {
+            return Err(actix_web::error::ErrorForbidden("language is disabled"));
+        }
+        if let Some(max) = policy.max_timeout_secs {
+            if payload.timeout.is_some() && timeout > Duration::from_secs(max) {
+                return Err(actix_web::error::ErrorBadRequest(
+                    "timeout exceeds the configured maximum for this language",
+                ));
+            }
+            timeout = timeout.min(Duration::from_secs(max));
+        }
+        if let Some(max) = policy.max_memory_bytes {
+            if payload.memory_limit.is_some() && limits.memory_bytes > max {
+                return Err(actix_web::error::ErrorBadRequest(
+                    "memory_limit exceeds the configured maximum for this language",
+                ));
+            }
+            limits.memory_bytes = limits.memory_bytes.min(max);
+        }
+    }
+
+    Ok((timeout, limits))
+}
+
+fn prepare_run(
+    payload: &Payload,
+    jobserver: &Jobserver,
+    timeout: Duration,
+    limits: Limits,
+) -> Result<PreparedRun, actix_web::Error> {
     if matches!(
         payload.lang_slug,
         Lang::Cpp
@@ -162,24 +438,36 @@ async fn run(
         actix_web::error::ErrorInternalServerError("internal error")
     })?;
 
+    let cgroup = CGroup::new(&limits).map_err(|e| {
+        log::error!("Create cgroup: {}", e);
+        actix_web::error::ErrorInternalServerError("internal error")
+    })?;
+
     let cwd = std::env::current_dir().map_err(|e| {
         log::error!("Get current dir: {}", e);
         actix_web::error::ErrorInternalServerError("internal error")
     })?;
 
-    let check_dir = if matches!(payload.lang_slug, Lang::Dart) {
-        "lib"
-    } else {
-        "check"
-    };
-
-    let check_path = tmp
-        .chroot()
-        .join(PathBuf::from_iter(cwd.components().skip(1))) // Drop the root component for correct join
-        .join(check_dir);
+    let check_dir = check_dir_for(&payload.lang_slug);
+    let check_path = overlay_check_path(tmp.chroot(), &cwd, check_dir);
 
     log::debug!("Check path is: {}", check_path.display());
 
+    if let Some(ref files_tar) = payload.files_tar {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(files_tar)
+            .map_err(|e| {
+                log::error!("Decode files_tar: {}", e);
+                actix_web::error::ErrorBadRequest("files_tar is not valid base64")
+            })?;
+        tar::Archive::new(std::io::Cursor::new(bytes))
+            .unpack(&check_path)
+            .map_err(|e| {
+                log::error!("Unpack files_tar: {}", e);
+                actix_web::error::ErrorBadRequest("files_tar is not a valid tar archive")
+            })?;
+    }
+
     if let Some(ref asserts) = payload.asserts {
         fs::write(check_path.join("asserts.json"), asserts.as_bytes()).map_err(|e| {
             log::error!("Write asserts file: {}", e);
@@ -187,7 +475,7 @@ async fn run(
         })?;
     }
 
-    let (solution_filename, checker_filename) = get_solution_chekcer_names(&payload);
+    let (solution_filename, checker_filename) = get_solution_chekcer_names(payload);
 
     fs::write(
         check_path.join(solution_filename),
@@ -209,12 +497,18 @@ async fn run(
         })?;
     }
 
+    let cpu_rlimit = timeout.as_secs().max(1);
+
+    let cgroup_path = cgroup.path().to_path_buf();
+
     let mut cmd = Command::new("make");
     unsafe {
         let chroot_path = tmp.chroot().clone();
         cmd.arg("--silent")
             .arg("test")
-            .stdin(Stdio::null())
+            .arg(jobserver.auth_arg())
+            .arg("-j")
+            .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .process_group(0)
@@ -232,17 +526,80 @@ async fn run(
                             | CloneFlags::CLONE_FILES
                             | CloneFlags::CLONE_NEWNS
                             // | CloneFlags::CLONE_NEWUSER TODO swift doesn't work with this
-                            // | CloneFlags::CLONE_NEWPID TODO figure out how to use that properly
+                            | CloneFlags::CLONE_NEWPID
                             | CloneFlags::CLONE_NEWNET,
                     )?;
+
+                    // Move ourselves into the cgroup now, synchronously,
+                    // before forking again. Cgroup membership is inherited
+                    // at fork time, so this guarantees the namespace's PID 1
+                    // (forked right below) — the process that actually execs
+                    // `make` — starts out confined. Doing this from the
+                    // async caller after `spawn()` returns would instead
+                    // race this very fork, and could lose.
+                    cgroup::add_pid_to_cgroup(&cgroup_path, nix::unistd::getpid())?;
+
+                    // CLONE_NEWPID only takes effect for children forked from
+                    // here on, so cross into the new namespace with another
+                    // fork: the child becomes its PID 1 and goes on to exec
+                    // `make` below, while we stay behind as a tiny init that
+                    // waits for it and relays signals (see `ns_init`).
+                    match unsafe { nix::unistd::fork() }? {
+                        ForkResult::Child => {}
+                        ForkResult::Parent { child } => ns_init(child),
+                    }
                 }
+                // Belt-and-suspenders caps on top of the cgroup, enforced by
+                // the kernel even if the process escapes the cgroup somehow.
+                setrlimit(Resource::RLIMIT_CPU, cpu_rlimit, cpu_rlimit)?;
+                setrlimit(Resource::RLIMIT_FSIZE, limits.output_bytes, limits.output_bytes)?;
+                setrlimit(Resource::RLIMIT_NPROC, limits.pids_max, limits.pids_max)?;
+                setrlimit(Resource::RLIMIT_AS, limits.memory_bytes, limits.memory_bytes)?;
                 // Chroot to put current execution in jail
                 nix::unistd::chroot(&chroot_path)?;
                 std::env::set_current_dir(&cwd).unwrap();
                 Ok(())
             });
     }
+
+    Ok(PreparedRun { tmp, cgroup, cmd })
+}
+
+#[post("/run")]
+async fn run(
+    web::Json(payload): web::Json<Payload>,
+    semaphore: web::Data<Semaphore>,
+    jobserver: web::Data<Jobserver>,
+    cache: web::Data<ResultCache>,
+    config: web::Data<Config>,
+) -> Result<web::Json<Response>, actix_web::Error> {
+    log::debug!("{}", serde_json::to_string(&payload).unwrap());
+
+    // Enforced before the cache is even consulted, so a disabled language
+    // or an over-cap request can't be served a stale 200 from a cache
+    // entry written before the policy changed (or by a looser request).
+    let (timeout, limits) = check_policy(&payload, &config)?;
+
+    let key = cache_key(&payload);
+    let key_lock = cache.lock_key(&key);
+    let key_guard = key_lock.acquire().await;
+
+    if let Some(cached) = cache.get::<Response>(&key) {
+        log::debug!("Cache hit for {}", key);
+        return Ok(web::Json(cached));
+    }
+
+    let _permit = semaphore.acquire().await.map_err(|e| {
+        log::error!("Acquire concurrency permit: {}", e);
+        actix_web::error::ErrorInternalServerError("internal error")
+    })?;
+
+    let PreparedRun { tmp, cgroup, mut cmd } = prepare_run(&payload, &jobserver, timeout, limits)?;
+
+    // The child moves itself into `cgroup` from its own `pre_exec`, before
+    // it can fork again — see the comment in `prepare_run`.
     let mut child = cmd.spawn().unwrap();
+    task::spawn(write_stdin(child.stdin.take().unwrap(), payload.stdin.clone()));
     let stdout_handle = task::spawn(read_stdio(child.stdout.take().unwrap()));
     let stderr_handle = task::spawn(read_stdio(child.stderr.take().unwrap()));
 
@@ -274,11 +631,197 @@ async fn run(
 
     log::debug!("STDOUT: {}", stdout);
     log::debug!("STDERR: {}", stderr);
-    Ok(web::Json(Response {
+
+    let artifacts_tar = if payload.return_artifacts.is_empty() {
+        None
+    } else {
+        std::env::current_dir().ok().and_then(|cwd| {
+            let check_dir = check_dir_for(&payload.lang_slug);
+            let check_path = overlay_check_path(tmp.upper(), &cwd, check_dir);
+            pack_artifacts(&check_path, &payload.return_artifacts)
+        })
+    };
+
+    let response = Response {
         exit_code: exit_code.code(),
         stdout,
         stderr,
-    }))
+        oom: cgroup.oom_killed(),
+        peak_memory: cgroup.peak_memory(),
+        artifacts_tar,
+    };
+
+    if let Err(e) = cache.put(&key, &response) {
+        log::error!("Write cache entry: {}", e);
+    }
+    drop(key_guard);
+
+    Ok(web::Json(response))
+}
+
+/// Serializes `value` as a single NDJSON line and sends it down `tx`.
+/// Returns `false` if the client has already gone away, so the caller can
+/// stop the run instead of continuing to feed a channel nobody reads.
+async fn send_line(tx: &mpsc::Sender<Result<web::Bytes, actix_web::Error>>, value: serde_json::Value) -> bool {
+    let mut line = serde_json::to_vec(&value).expect("serializing a json value cannot fail");
+    line.push(b'\n');
+    tx.send(Ok(web::Bytes::from(line))).await.is_ok()
+}
+
+/// Kills `child`'s whole process group, logging (rather than panicking) on
+/// failure since this already runs off the back of another abnormal event
+/// (a timeout or a disconnected client).
+fn kill_child_group(child: &Child) {
+    let pid = child.id().unwrap() as i32;
+    if let Err(e) = signal::killpg(Pid::from_raw(pid), signal::SIGKILL) {
+        log::error!("Cannot kill child group: {}", e);
+    }
+}
+
+/// Reads `child`'s stdout/stderr as they arrive and forwards each chunk down
+/// `tx` as an NDJSON line, then forwards the final outcome. Runs until the
+/// child exits or is killed, either on timeout or because the client
+/// disconnected (detected via a failed send on `tx`) — in both cases the
+/// child's process group is killed so the jail, cgroup and concurrency
+/// permit aren't held for a run nobody is waiting on anymore.
+async fn stream_child(
+    tmp: TmpDir,
+    cgroup: CGroup,
+    permit: tokio::sync::OwnedSemaphorePermit,
+    mut child: Child,
+    timeout: Duration,
+    return_artifacts: Vec<String>,
+    check_dir: &'static str,
+    tx: mpsc::Sender<Result<web::Bytes, actix_web::Error>>,
+) {
+    // Keep the jail, cgroup and concurrency permit alive for as long as the
+    // child (and its descendants) might still be touching them.
+    let _permit = permit;
+
+    let mut stdout = child.stdout.take().unwrap();
+    let mut stderr = child.stderr.take().unwrap();
+    let mut stdout_buf = [0u8; 8192];
+    let mut stderr_buf = [0u8; 8192];
+    let mut stdout_open = true;
+    let mut stderr_open = true;
+    let mut exit_status = None;
+
+    let sleep = time::sleep(timeout);
+    tokio::pin!(sleep);
+
+    while exit_status.is_none() {
+        tokio::select! {
+            _ = &mut sleep => {
+                log::warn!("Timeout streaming run");
+                kill_child_group(&child);
+                send_line(&tx, serde_json::json!({"event": "timeout"})).await;
+                return;
+            }
+            n = stdout.read(&mut stdout_buf), if stdout_open => {
+                match n {
+                    Ok(0) => stdout_open = false,
+                    Ok(n) => {
+                        if !send_line(&tx, serde_json::json!({"stream": "stdout", "data": String::from_utf8_lossy(&stdout_buf[..n])})).await {
+                            log::warn!("Client disconnected, stopping stream");
+                            kill_child_group(&child);
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("Read stdout: {}", e);
+                        stdout_open = false;
+                    }
+                }
+            }
+            n = stderr.read(&mut stderr_buf), if stderr_open => {
+                match n {
+                    Ok(0) => stderr_open = false,
+                    Ok(n) => {
+                        if !send_line(&tx, serde_json::json!({"stream": "stderr", "data": String::from_utf8_lossy(&stderr_buf[..n])})).await {
+                            log::warn!("Client disconnected, stopping stream");
+                            kill_child_group(&child);
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("Read stderr: {}", e);
+                        stderr_open = false;
+                    }
+                }
+            }
+            status = child.wait(), if !stdout_open && !stderr_open => {
+                exit_status = Some(status);
+            }
+        }
+    }
+
+    match exit_status.unwrap() {
+        Ok(status) => {
+            let artifacts_tar = if return_artifacts.is_empty() {
+                None
+            } else {
+                std::env::current_dir().ok().and_then(|cwd| {
+                    let check_path = overlay_check_path(tmp.upper(), &cwd, check_dir);
+                    pack_artifacts(&check_path, &return_artifacts)
+                })
+            };
+            send_line(
+                &tx,
+                serde_json::json!({
+                    "exit_code": status.code(),
+                    "oom": cgroup.oom_killed(),
+                    "peak_memory": cgroup.peak_memory(),
+                    "artifacts_tar": artifacts_tar,
+                }),
+            )
+            .await
+        }
+        Err(e) => {
+            log::error!("Wait for child: {}", e);
+            send_line(&tx, serde_json::json!({"exit_code": null})).await;
+        }
+    }
+    // tmp is kept alive (overlay mounted) until here, covering the whole
+    // streamed run plus the artifact read-back above.
+    drop(tmp);
+}
+
+#[post("/run/stream")]
+async fn run_stream(
+    web::Json(payload): web::Json<Payload>,
+    semaphore: web::Data<Semaphore>,
+    jobserver: web::Data<Jobserver>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, actix_web::Error> {
+    log::debug!("{}", serde_json::to_string(&payload).unwrap());
+    let (timeout, limits) = check_policy(&payload, &config)?;
+    let permit = semaphore.into_inner().acquire_owned().await.map_err(|e| {
+        log::error!("Acquire concurrency permit: {}", e);
+        actix_web::error::ErrorInternalServerError("internal error")
+    })?;
+
+    let PreparedRun { tmp, cgroup, mut cmd } = prepare_run(&payload, &jobserver, timeout, limits)?;
+
+    // The child moves itself into `cgroup` from its own `pre_exec`, before
+    // it can fork again — see the comment in `prepare_run`.
+    let mut child = cmd.spawn().unwrap();
+    task::spawn(write_stdin(child.stdin.take().unwrap(), payload.stdin.clone()));
+    let (tx, rx) = mpsc::channel(16);
+    let check_dir = check_dir_for(&payload.lang_slug);
+    task::spawn(stream_child(
+        tmp,
+        cgroup,
+        permit,
+        child,
+        timeout,
+        payload.return_artifacts.clone(),
+        check_dir,
+        tx,
+    ));
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(ReceiverStream::new(rx)))
 }
 
 #[get("/health")]
@@ -286,6 +829,37 @@ async fn health() -> impl Responder {
     HttpResponse::Ok()
 }
 
+/// Rejects `/run` and `/run/stream` unless the caller presents a token from
+/// `Config`'s list as `Authorization: Bearer <token>`. Stays a no-op if the
+/// config has no tokens configured, so an unconfigured service behaves the
+/// same as before this middleware existed.
+async fn require_bearer_token(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let config = req
+        .app_data::<web::Data<Config>>()
+        .expect("Config must be registered as app_data")
+        .clone();
+
+    if config.tokens_configured() {
+        let authorized = req
+            .headers()
+            .get(actix_web::http::header::AUTHORIZATION)
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .is_some_and(|token| config.is_authorized(token));
+
+        if !authorized {
+            return Err(actix_web::error::ErrorUnauthorized(
+                "missing or invalid bearer token",
+            ));
+        }
+    }
+
+    next.call(req).await
+}
+
 fn main() -> anyhow::Result<()> {
     env_logger::init();
     log::info!("Runner version {}", VERSION);
@@ -305,12 +879,41 @@ fn main() -> anyhow::Result<()> {
     let rt = Runtime::new()?;
     rt.block_on(async {
         log::info!("Starting runner service");
-        HttpServer::new(|| {
+
+        let concurrency = concurrency_from_env("RUNNER_MAX_CONCURRENCY");
+        let make_jobs = concurrency_from_env("RUNNER_MAKE_JOBS");
+        log::info!(
+            "Admitting up to {} concurrent runs, sharing {} make job slots",
+            concurrency,
+            make_jobs
+        );
+        let semaphore = web::Data::new(Semaphore::new(concurrency));
+        let jobserver = web::Data::new(Jobserver::new(make_jobs)?);
+        let cache_dir = std::env::var("RUNNER_CACHE_DIR")
+            .unwrap_or_else(|_| "/tmp/runner-result-cache".to_string());
+        let cache = web::Data::new(ResultCache::new(PathBuf::from(cache_dir))?);
+        let config = web::Data::new(Config::load()?);
+        if !config.tokens_configured() {
+            log::warn!("No bearer tokens configured, /run and /run/stream are open to anyone");
+        }
+
+        HttpServer::new(move || {
             let json_config = web::JsonConfig::default().limit(10485760);
             App::new()
                 .app_data(json_config)
-                .service(run)
+                .app_data(semaphore.clone())
+                .app_data(jobserver.clone())
+                .app_data(cache.clone())
+                .app_data(config.clone())
                 .service(health)
+                // Registered after `health`: an empty-prefix scope matches
+                // every path, so anything registered after it is unreachable.
+                .service(
+                    web::scope("")
+                        .wrap(actix_web::middleware::from_fn(require_bearer_token))
+                        .service(run)
+                        .service(run_stream),
+                )
         })
         .bind(("0.0.0.0", 8000))?
         .run()
@@ -319,3 +922,87 @@ fn main() -> anyhow::Result<()> {
         Ok(())
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(lang: Lang) -> Payload {
+        Payload {
+            timeout: None,
+            solution_text: "solution".to_string(),
+            lang_slug: lang,
+            asserts: None,
+            checker_text: None,
+            memory_limit: None,
+            pids_limit: None,
+            cpu_limit: None,
+            stdin: None,
+            files_tar: None,
+            return_artifacts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn resolve_artifact_path_accepts_relative_paths_inside_check_path() {
+        let check_path = std::path::Path::new("/tmp/check");
+        assert_eq!(
+            resolve_artifact_path(check_path, "output.txt"),
+            Some(check_path.join("output.txt"))
+        );
+        assert_eq!(
+            resolve_artifact_path(check_path, "nested/output.txt"),
+            Some(check_path.join("nested/output.txt"))
+        );
+    }
+
+    #[test]
+    fn resolve_artifact_path_rejects_absolute_paths() {
+        let check_path = std::path::Path::new("/tmp/check");
+        assert_eq!(resolve_artifact_path(check_path, "/etc/passwd"), None);
+    }
+
+    #[test]
+    fn resolve_artifact_path_rejects_parent_dir_traversal() {
+        let check_path = std::path::Path::new("/tmp/check");
+        assert_eq!(resolve_artifact_path(check_path, "../../etc/passwd"), None);
+        assert_eq!(resolve_artifact_path(check_path, "nested/../../escape"), None);
+    }
+
+    #[test]
+    fn cache_key_is_deterministic() {
+        let p = payload(Lang::Ruby);
+        assert_eq!(cache_key(&p), cache_key(&p));
+    }
+
+    #[test]
+    fn cache_key_differs_between_none_and_some_zero_memory_limit() {
+        let mut a = payload(Lang::Ruby);
+        let mut b = payload(Lang::Ruby);
+        a.memory_limit = None;
+        b.memory_limit = Some(0);
+        assert_ne!(cache_key(&a), cache_key(&b));
+    }
+
+    #[test]
+    fn cache_key_differs_by_resource_limits() {
+        let base = payload(Lang::Ruby);
+        let mut pids = payload(Lang::Ruby);
+        pids.pids_limit = Some(16);
+        let mut cpu = payload(Lang::Ruby);
+        cpu.cpu_limit = Some(50);
+
+        let base_key = cache_key(&base);
+        assert_ne!(base_key, cache_key(&pids));
+        assert_ne!(base_key, cache_key(&cpu));
+    }
+
+    #[test]
+    fn cache_key_differs_by_solution_text() {
+        let mut a = payload(Lang::Ruby);
+        let mut b = payload(Lang::Ruby);
+        a.solution_text = "puts 1".to_string();
+        b.solution_text = "puts 2".to_string();
+        assert_ne!(cache_key(&a), cache_key(&b));
+    }
+}
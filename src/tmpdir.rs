@@ -6,6 +6,7 @@ use uuid::Uuid;
 pub struct TmpDir {
     base: PathBuf,
     chroot: PathBuf,
+    upper: PathBuf,
 }
 
 impl TmpDir {
@@ -24,11 +25,6 @@ impl TmpDir {
         let merged = path.join("merged");
         std::fs::create_dir(&merged)?;
 
-        let this = Self {
-            base: path,
-            chroot: merged.clone(),
-        };
-
         // Mount root as overlay
         #[allow(unused)]
         let opts = format!(
@@ -37,6 +33,12 @@ impl TmpDir {
             work.display()
         );
 
+        let this = Self {
+            base: path,
+            chroot: merged.clone(),
+            upper,
+        };
+
         // Only Linux has overlayfs, and this code is supposed
         // to work only in container environment.
         // Condition here exists only for the purpose of muting errors
@@ -81,6 +83,12 @@ impl TmpDir {
     pub fn chroot(&self) -> &PathBuf {
         &self.chroot
     }
+
+    /// Returns the overlay's upper dir, i.e. everything the check wrote or
+    /// changed relative to the original root.
+    pub fn upper(&self) -> &PathBuf {
+        &self.upper
+    }
 }
 
 impl Drop for TmpDir {